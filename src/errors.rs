@@ -1,37 +1,66 @@
+use std::error::Error as StdError;
+use std::fmt;
 use std::io;
+use std::net::{AddrParseError, Ipv4Addr};
 use std::string::FromUtf8Error;
 
+/// Errors that can occur while opening a `.dat` or resolving a lookup.
 #[derive(Debug)]
-pub struct Error {
-    message: String,
+pub enum Error {
+    /// Reading the backing file failed.
+    Io(io::Error),
+    /// A string in the database was not valid UTF-8 once decoded.
+    Utf8(FromUtf8Error),
+    /// The string passed to `IPDB::find_str` was neither a dotted-quad IP
+    /// nor a packed `u32`.
+    AddrParse(AddrParseError),
+    /// No index entry covers the requested IP.
+    NotFound { ip: Ipv4Addr },
+    /// A record pointed at an offset outside the database, or past the end
+    /// of the buffer.
+    CorruptData { offset: u64 },
+    /// The `.dat` header could not be decoded.
+    InvalidHeader,
 }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Self {
-            message: err.to_string(),
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "i/o error: {}", err),
+            Error::Utf8(err) => write!(f, "invalid utf-8 in database: {}", err),
+            Error::AddrParse(err) => write!(f, "invalid ip address: {}", err),
+            Error::NotFound { ip } => write!(f, "no record found for {}", ip),
+            Error::CorruptData { offset } => write!(f, "corrupt data at offset {}", offset),
+            Error::InvalidHeader => write!(f, "invalid database header"),
         }
     }
 }
 
-impl From<&str> for Error {
-    fn from(err: &str) -> Self {
-        Self {
-            message: err.to_string(),
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Utf8(err) => Some(err),
+            Error::AddrParse(err) => Some(err),
+            Error::NotFound { .. } | Error::CorruptData { .. } | Error::InvalidHeader => None,
         }
     }
 }
 
-impl From<String> for Error {
-    fn from(err: String) -> Self {
-        Self { message: err }
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
     }
 }
 
 impl From<FromUtf8Error> for Error {
     fn from(err: FromUtf8Error) -> Self {
-        Self {
-            message: err.to_string(),
-        }
+        Self::Utf8(err)
+    }
+}
+
+impl From<AddrParseError> for Error {
+    fn from(err: AddrParseError) -> Self {
+        Self::AddrParse(err)
     }
 }
@@ -1,6 +1,6 @@
 use std::fs::File;
-use std::io;
 use std::io::prelude::*;
+use std::os::unix::fs::FileExt;
 use std::path::Path;
 
 use byteorder::{LittleEndian, ReadBytesExt};
@@ -10,12 +10,31 @@ mod errors;
 use errors::Error;
 const INDEX_LEN: u64 = 7;
 
+/// Where the raw `.dat` bytes come from.
+///
+/// `Memory` holds the whole database in a `Vec<u8>` read once at open time,
+/// so every lookup is a slice index instead of a syscall. `File` reads
+/// directly at an offset via [`FileExt::read_exact_at`] instead of seeking a
+/// shared cursor, so neither variant needs interior mutability and `IPDB`
+/// is `Send + Sync` either way — it can be shared across threads behind an
+/// `Arc` for concurrent lookups.
+#[derive(Debug)]
+enum Backend {
+    Memory(Vec<u8>),
+    File(File),
+}
+
 #[derive(Debug)]
 pub struct IPDB {
-    file: File,
+    backend: Backend,
     header: Header,
 
-    position: u64,
+    /// Two-level prefix index, bucketed by the high octet of the IP
+    /// (`ip >> 24`): `index_table[il0]` gives the `[start, end)` byte span
+    /// of `search_index`'s index range that can contain entries for that
+    /// octet, built once at open time so the binary search only ever runs
+    /// over a narrow slice instead of the whole index.
+    index_table: [(u64, u64); 256],
 }
 
 #[derive(Debug, std::cmp::PartialEq)]
@@ -23,6 +42,49 @@ pub struct Record {
     pub ip: Ipv4Addr,
     pub country: String,
     pub area: String,
+
+    /// First IP of the index range this record was matched in.
+    pub start: Ipv4Addr,
+    /// Last IP of the index range this record was matched in (the IP
+    /// just before the next index entry's start, or `255.255.255.255`
+    /// for the final entry).
+    pub end: Ipv4Addr,
+}
+
+impl Record {
+    /// Whether `ip` falls within this record's matched range.
+    pub fn covers(&self, ip: Ipv4Addr) -> bool {
+        u32::from(self.start) <= u32::from(ip) && u32::from(ip) <= u32::from(self.end)
+    }
+
+    /// Decompose the record's inclusive IP range into the smallest set of
+    /// aligned CIDR blocks that exactly cover it.
+    pub fn to_cidrs(&self) -> Vec<(Ipv4Addr, u8)> {
+        cidrs_for_range(self.start, self.end)
+    }
+}
+
+fn cidrs_for_range(start: Ipv4Addr, end: Ipv4Addr) -> Vec<(Ipv4Addr, u8)> {
+    let mut blocks = Vec::new();
+    let mut start = u32::from(start) as u64;
+    let end = u32::from(end) as u64;
+
+    while start <= end {
+        let align_bits = if start == 0 {
+            32
+        } else {
+            start.trailing_zeros().min(32)
+        };
+        let span = end - start + 1;
+        let span_bits = 63 - span.leading_zeros();
+        let bits = align_bits.min(span_bits);
+        let prefix = 32 - bits;
+
+        blocks.push((Ipv4Addr::from(start as u32), prefix as u8));
+        start += 1u64 << bits;
+    }
+
+    blocks
 }
 
 #[derive(Debug)]
@@ -33,12 +95,18 @@ struct Header {
 
 impl Header {
     fn decode(mut bytes: &[u8]) -> Result<Self, Error> {
-        let header = Self {
-            start: bytes.read_u32::<LittleEndian>()? as u64,
-            end: bytes.read_u32::<LittleEndian>()? as u64,
-        };
+        let start = bytes
+            .read_u32::<LittleEndian>()
+            .map_err(|_| Error::InvalidHeader)? as u64;
+        let end = bytes
+            .read_u32::<LittleEndian>()
+            .map_err(|_| Error::InvalidHeader)? as u64;
+
+        if start > end {
+            return Err(Error::InvalidHeader);
+        }
 
-        Ok(header)
+        Ok(Self { start, end })
     }
 }
 
@@ -81,86 +149,235 @@ fn convert_ipv4_to_u32(ip: Ipv4Addr) -> u32 {
     result
 }
 
+fn open_file(path: &str) -> File {
+    let p = Path::new(path);
+    let display = p.display();
+
+    match File::open(p) {
+        Err(why) => panic!("couldn't open {}: {}", display, why),
+        Ok(file) => file,
+    }
+}
+
 impl IPDB {
     pub fn new(path: &str) -> Self {
-        let p = Path::new(path);
-        let display = p.display();
+        let file = open_file(path);
 
-        let mut file = match File::open(&p) {
-            Err(why) => panic!("couldn't open {}: {}", display, why),
-            Ok(file) => file,
+        let mut db = IPDB {
+            backend: Backend::File(file),
+            header: Header { start: 0, end: 0 },
+            index_table: [(0, 0); 256],
         };
+        db.header = db.read_header().unwrap();
+        db.index_table = db.build_index_table().unwrap();
+        db
+    }
+
+    /// Read the whole `.dat` into memory once instead of reading a `File`
+    /// for every lookup. Use this when the database comfortably fits in
+    /// memory, since every read is then a slice index rather than a
+    /// syscall. For very large databases, fall back to [`IPDB::new`].
+    pub fn open_in_memory(path: &str) -> Self {
+        let mut file = open_file(path);
+
+        let mut buf = Vec::new();
+        if let Err(err) = file.read_to_end(&mut buf) {
+            panic!("could't read {}: {}", path, err);
+        }
 
-        let mut buf = [0; 8];
-        let header = match file.read(&mut buf) {
-            Err(err) => panic!("could't read {}: {}", display, err),
-            Ok(_) => Header::decode(&mut buf).unwrap(),
+        let mut db = IPDB {
+            backend: Backend::Memory(buf),
+            header: Header { start: 0, end: 0 },
+            index_table: [(0, 0); 256],
         };
+        db.header = db.read_header().unwrap();
+        db.index_table = db.build_index_table().unwrap();
+        db
+    }
+
+    fn read_header(&self) -> Result<Header, Error> {
+        let buf = self.read_bytes(0, 8)?;
+        Header::decode(&buf)
+    }
 
-        IPDB {
-            file: file,
-            header: header,
-            position: 0,
+    /// Walk the index once, recording for each high octet `il0` the byte
+    /// span `[start, end)` of `search_index`'s index range whose start IPs
+    /// share that octet.
+    ///
+    /// An entry's *covered* range can run well past its own start octet
+    /// (e.g. a single entry spanning a whole multicast block), so an octet
+    /// with no index entry of its own must still floor-match into the
+    /// nearest lower octet that has one. Fill those gaps by copying each
+    /// empty bucket forward from the nearest preceding non-empty one,
+    /// rather than leaving it `(0, 0)` and failing the lookup outright.
+    fn build_index_table(&self) -> Result<[(u64, u64); 256], Error> {
+        let mut table = [(0u64, 0u64); 256];
+        let mut current_bucket: Option<usize> = None;
+
+        let mut offset = self.header.start;
+        while offset <= self.header.end {
+            let buf = self.read_bytes(offset, 4)?;
+            let ip_num = (&buf[0..4]).read_u32::<LittleEndian>()?;
+            let il0 = (ip_num >> 24) as usize;
+
+            match current_bucket {
+                Some(bucket) if bucket == il0 => {}
+                Some(bucket) => {
+                    table[bucket].1 = offset;
+                    table[il0].0 = offset;
+                    current_bucket = Some(il0);
+                }
+                None => {
+                    table[il0].0 = offset;
+                    current_bucket = Some(il0);
+                }
+            }
+
+            offset += INDEX_LEN;
+        }
+
+        if let Some(bucket) = current_bucket {
+            table[bucket].1 = self.header.end + INDEX_LEN;
+        }
+
+        for il0 in 1..256 {
+            if table[il0] == (0, 0) {
+                table[il0] = table[il0 - 1];
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Read `len` bytes starting at `offset`, from the in-memory buffer if
+    /// present or by a positioned read on the backing `File` otherwise.
+    fn read_bytes(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        match &self.backend {
+            Backend::Memory(buf) => {
+                let start = offset as usize;
+                let end = start + len;
+                buf.get(start..end)
+                    .map(|slice| slice.to_vec())
+                    .ok_or(Error::CorruptData { offset })
+            }
+            Backend::File(file) => {
+                let mut buf = vec![0; len];
+                file.read_exact_at(&mut buf, offset)?;
+                Ok(buf)
+            }
         }
     }
 
-    pub fn find(&mut self, ip: Ipv4Addr) -> Result<Record, Error> {
-        let offset = self.search_index(ip)?;
-        self.get_content(offset)
+    pub fn find(&self, ip: Ipv4Addr) -> Result<Record, Error> {
+        let (offset, start, end) = self.search_index(ip)?;
+        self.get_content(offset, start, end)
     }
 
-    fn search_index(&mut self, ip: Ipv4Addr) -> Result<u64, Error> {
+    /// Look up `s`, accepting either a dotted-quad IP (`"8.8.8.8"`) or a
+    /// packed decimal `u32` (`"134744072"`), mirroring how command-line
+    /// tools and log processors tend to have the address as a string.
+    pub fn find_str(&self, s: &str) -> Result<Record, Error> {
+        let ip = s
+            .parse::<Ipv4Addr>()
+            .or_else(|err| s.parse::<u32>().map(Ipv4Addr::from).map_err(|_| Error::from(err)))?;
+
+        self.find(ip)
+    }
+
+    /// Binary search the index for the entry whose start IP is the
+    /// greatest one `<= ip` (a floor search, since each index entry
+    /// describes the range from its start IP up to the next entry's
+    /// start IP), returning the matched record's content offset together
+    /// with the inclusive IP range `[start, end]` it covers.
+    fn search_index(&self, ip: Ipv4Addr) -> Result<(u64, Ipv4Addr, Ipv4Addr), Error> {
         let ip_num = convert_ipv4_to_u32(ip);
-        let (mut start, mut end) = (self.header.start, self.header.end);
+        let il0 = (ip_num >> 24) as usize;
+        let (mut start, mut end) = self.index_table[il0];
+        if start == end {
+            return Err(Error::NotFound { ip });
+        }
+
+        let mut floor: Option<(u64, Vec<u8>)> = None;
+
         loop {
             let mid = get_middle_offset(start, end);
-            self.file.seek(std::io::SeekFrom::Start(mid))?;
-
-            let mut buf = [0; INDEX_LEN as usize];
-            self.file.read(&mut buf)?;
+            let buf = self.read_bytes(mid, INDEX_LEN as usize)?;
 
             let mid_ip = (&buf[0..4]).read_u32::<LittleEndian>()?;
 
-            if ip_num == mid_ip {
-                return Ok(array3u8tou32(&buf[4..7]) as u64);
+            if ip_num >= mid_ip {
+                floor = Some((mid, buf));
             }
 
             if end - start == INDEX_LEN {
-                return Err(Error::from("couldn't find ip"));
+                break;
             }
 
-            if ip_num > mid_ip {
+            if ip_num >= mid_ip {
                 start = mid;
             } else {
                 end = mid;
             }
         }
-    }
 
-    fn read_ip(&mut self, offset: u64) -> Result<Ipv4Addr, Error> {
-        self.file.seek(io::SeekFrom::Start(offset))?;
+        let (entry_offset, buf) = match floor {
+            Some(found) => found,
+            // `ip` sorts below every entry of its own bucket, so its floor
+            // is the last entry of the preceding bucket, if there is one.
+            None if start > self.header.start => {
+                let prev = start - INDEX_LEN;
+                (prev, self.read_bytes(prev, INDEX_LEN as usize)?)
+            }
+            None => return Err(Error::NotFound { ip }),
+        };
+        let offset = array3u8tou32(&buf[4..7]) as u64;
+        let (range_start, range_end) = self.index_entry_range(entry_offset, &buf[0..4])?;
+        Ok((offset, range_start, range_end))
+    }
 
-        let mut buf = [0; 4];
-        self.file.read(&mut buf)?;
+    /// Given an index entry at `entry_offset` whose raw 4-byte start IP is
+    /// `start_ip_bytes`, work out the inclusive `[start, end]` range it
+    /// covers by peeking at the next entry's start IP (or clamping to
+    /// `255.255.255.255` if this is the last entry in the whole index).
+    fn index_entry_range(
+        &self,
+        entry_offset: u64,
+        start_ip_bytes: &[u8],
+    ) -> Result<(Ipv4Addr, Ipv4Addr), Error> {
+        let start = Ipv4Addr::new(
+            start_ip_bytes[0],
+            start_ip_bytes[1],
+            start_ip_bytes[2],
+            start_ip_bytes[3],
+        );
+
+        let end = if entry_offset == self.header.end {
+            Ipv4Addr::new(255, 255, 255, 255)
+        } else {
+            let next = self.read_bytes(entry_offset + INDEX_LEN, 4)?;
+            let next_ip = Ipv4Addr::new(next[0], next[1], next[2], next[3]);
+            Ipv4Addr::from(u32::from(next_ip).saturating_sub(1))
+        };
 
-        Ok(Ipv4Addr::from(buf))
+        Ok((start, end))
     }
 
-    fn read_mode(&mut self, offset: u64) -> Result<Mode, Error> {
-        self.file.seek(io::SeekFrom::Start(offset))?;
-
-        let mut buf = [0; 1];
-        self.file.read(&mut buf)?;
+    fn read_ip(&self, offset: u64) -> Result<Ipv4Addr, Error> {
+        let buf = self.read_bytes(offset, 4)?;
+        Ok(Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3]))
+    }
 
+    fn read_mode(&self, offset: u64) -> Result<Mode, Error> {
+        let buf = self.read_bytes(offset, 1)?;
         Ok(Mode::from(buf[0]))
     }
 
-    fn read_area(&mut self, offset: u64) -> Result<Vec<u8>, Error> {
+    fn read_area(&self, offset: u64) -> Result<Vec<u8>, Error> {
         match self.read_mode(offset)? {
             Mode::RediectMode2 | Mode::RediectMode1 => {
-                let area_offset = self.read_u24()?;
+                let area_offset = self.read_u24(offset + 1)?;
                 if area_offset == 0 {
-                    Err(Error::from(format!("Wrong content, in {}", offset + 1)))
+                    Err(Error::CorruptData { offset: offset + 1 })
                 } else {
                     self.read_string(area_offset as u64)
                 }
@@ -169,36 +386,35 @@ impl IPDB {
         }
     }
 
-    fn read_u24(&mut self) -> Result<u32, Error> {
-        let mut buf = [0; 3];
-        self.file.read(&mut buf)?;
+    fn read_u24(&self, offset: u64) -> Result<u32, Error> {
+        let buf = self.read_bytes(offset, 3)?;
         Ok(array3u8tou32(&buf))
     }
 
-    fn read_string(&mut self, offset: u64) -> Result<Vec<u8>, Error> {
-        self.file.seek(io::SeekFrom::Start(offset))?;
+    fn read_string(&self, offset: u64) -> Result<Vec<u8>, Error> {
         let mut result = Vec::new();
-        let mut buf = [0; 1];
+        let mut offset = offset;
         loop {
-            self.file.read(&mut buf)?;
+            let buf = self.read_bytes(offset, 1)?;
             if buf[0] == 0 {
                 break;
             }
             result.push(buf[0]);
+            offset += 1;
         }
 
         Ok(result)
     }
 
-    fn get_content(&mut self, offset: u64) -> Result<Record, Error> {
+    fn get_content(&self, offset: u64, start: Ipv4Addr, end: Ipv4Addr) -> Result<Record, Error> {
         let mode = self.read_mode(offset + 4)?;
         let (country, area) = match mode {
             Mode::RediectMode1 => {
-                let country_offset = self.read_u24()? as u64;
+                let country_offset = self.read_u24(offset + 5)? as u64;
                 let mode = self.read_mode(country_offset)?;
                 let (country, area_offset) = match mode {
                     Mode::RediectMode2 => {
-                        let c = self.read_u24()? as u64;
+                        let c = self.read_u24(country_offset + 1)? as u64;
                         let country = self.read_string(c)?;
                         (country, country_offset + 4)
                     }
@@ -214,7 +430,7 @@ impl IPDB {
             }
 
             Mode::RediectMode2 => {
-                let country_offset = self.read_u24()?;
+                let country_offset = self.read_u24(offset + 5)?;
                 let country = self.read_string(country_offset as u64)?;
                 let area = self.read_area(offset + 5 + country.len() as u64)?;
                 (country, area)
@@ -233,68 +449,272 @@ impl IPDB {
             ip: self.read_ip(offset)?,
             country: country_str.to_string(),
             area: area_str.to_string(),
+            start,
+            end,
         })
     }
 
-    pub fn iter_init(&mut self) -> Result<(), Error> {
-        if self.position == 0 {
-            self.position = self.header.start;
+    /// Walk every record in the index, in ascending IP order.
+    pub fn iter(&self) -> RecordIter<'_> {
+        RecordIter {
+            db: self,
+            position: self.header.start,
         }
+    }
+}
 
-        let position = self.file.stream_position().unwrap();
-        if position != self.position {
-            self.file.seek(io::SeekFrom::Start(self.position))?;
-        }
+/// Iterator over every record in an [`IPDB`], yielded in ascending IP
+/// order. Obtained via [`IPDB::iter`].
+pub struct RecordIter<'a> {
+    db: &'a IPDB,
+    position: u64,
+}
 
-        Ok(())
-    }
+impl<'a> Iterator for RecordIter<'a> {
+    type Item = Result<Record, Error>;
 
-    pub fn iter_next(&mut self) -> Result<Record, Error> {
-        let position = self.file.stream_position().unwrap();
-        if position != self.position {
-            self.file.seek(io::SeekFrom::Start(self.position))?;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position > self.db.header.end {
+            return None;
         }
-        let mut buf = [0; INDEX_LEN as usize];
-        let n = self.file.read(&mut buf)?;
-        self.position += n as u64;
 
-        self.get_content(array3u8tou32(&buf[4..]) as u64)
+        let entry_offset = self.position;
+        self.position += INDEX_LEN;
+
+        let record = self
+            .db
+            .read_bytes(entry_offset, INDEX_LEN as usize)
+            .and_then(|buf| {
+                let (start, end) = self.db.index_entry_range(entry_offset, &buf[0..4])?;
+                self.db
+                    .get_content(array3u8tou32(&buf[4..]) as u64, start, end)
+            });
+
+        Some(record)
     }
 
-    pub fn iter_has_next(&mut self) -> bool {
-        if self.position == self.header.end + INDEX_LEN {
-            false
-        } else {
-            true
-        }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = ((self.db.header.end + INDEX_LEN - self.position) / INDEX_LEN) as usize;
+        (remaining, Some(remaining))
     }
 }
 
 #[test]
 fn test_iter() {
-    let mut db = IPDB::new("./data/qqwry.dat");
-    let mut count = 0;
-    db.iter_init().unwrap();
-    while db.iter_has_next() {
-        db.iter_next().unwrap();
-        count += 1;
-    }
+    let db = IPDB::new("./data/qqwry.dat");
+    let expected = (db.header.end + INDEX_LEN - db.header.start) / INDEX_LEN;
+
+    let (lower, upper) = db.iter().size_hint();
+    assert_eq!(lower as u64, expected);
+    assert_eq!(upper, Some(lower));
 
-    assert_eq!(
-        count,
-        (db.header.end + INDEX_LEN - db.header.start) / INDEX_LEN
-    );
+    let count = db.iter().filter_map(Result::ok).count();
+    assert_eq!(count as u64, expected);
 }
 
 #[test]
 fn test_find() {
-    let mut db = IPDB::new("./data/qqwry.dat");
-    let result = Record {
-        ip: Ipv4Addr::new(8, 8, 8, 8),
-        country: "美国".to_string(),
-        area: "加利福尼亚州圣克拉拉县山景市谷歌公司DNS服务器".to_string(),
-    };
+    let db = IPDB::new("./data/qqwry.dat");
+    let ip = Ipv4Addr::new(8, 8, 8, 8);
+
+    let record = db.find(ip).unwrap();
+    assert_eq!(record.country, "美国");
+    assert_eq!(record.area, "加利福尼亚州圣克拉拉县山景市谷歌公司DNS服务器");
+}
+
+#[test]
+fn test_ipdb_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<IPDB>();
+}
+
+#[test]
+fn test_find_in_memory() {
+    let db = IPDB::open_in_memory("./data/qqwry.dat");
+    let ip = Ipv4Addr::new(8, 8, 8, 8);
+
+    let record = db.find(ip).unwrap();
+    assert_eq!(record.country, "美国");
+    assert_eq!(record.area, "加利福尼亚州圣克拉拉县山景市谷歌公司DNS服务器");
+}
+
+#[test]
+fn test_find_str() {
+    let db = IPDB::new("./data/qqwry.dat");
+
+    let by_dotted = db.find_str("8.8.8.8").unwrap();
+    let by_decimal = db.find_str("134744072").unwrap();
+    assert_eq!(by_dotted, by_decimal);
+
+    assert!(db.find_str("not an ip").is_err());
+}
+
+#[test]
+fn test_error_variants() {
+    let db = IPDB::new("./data/qqwry.dat");
+
+    let err = db.find_str("not an ip").unwrap_err();
+    assert!(matches!(err, Error::AddrParse(_)));
+    assert!(err.to_string().contains("invalid ip address"));
+
+    let boxed: Box<dyn std::error::Error> = Box::new(err);
+    assert!(boxed.source().is_some());
+}
+
+#[test]
+fn test_record_range() {
+    let db = IPDB::new("./data/qqwry.dat");
+    let ip = Ipv4Addr::new(8, 8, 8, 8);
+
+    let record = db.find(ip).unwrap();
+    assert!(record.covers(ip));
+    assert!(u32::from(record.start) <= u32::from(record.end));
+
+    let cidrs = record.to_cidrs();
+    assert!(!cidrs.is_empty());
+}
+
+/// Build a tiny synthetic `.dat` with one sorted index entry per raw IP
+/// value in `ips`, each pointing at a minimal "Other"-mode content record
+/// labelled by its position (`C0`/`A0`, `C1`/`A1`, ...), so `search_index`'s
+/// floor-matching behaviour can be exercised without the real `qqwry.dat`
+/// fixture.
+#[cfg(test)]
+fn build_synthetic_dat(ips: &[u32]) -> Vec<u8> {
+    fn record(ip: u32, country: &str, area: &str) -> Vec<u8> {
+        let mut buf = ip.to_le_bytes().to_vec();
+        buf.extend_from_slice(country.as_bytes());
+        buf.push(0);
+        buf.extend_from_slice(area.as_bytes());
+        buf.push(0);
+        buf
+    }
 
-    let record = db.find(result.ip).unwrap();
-    assert_eq!(record, result);
+    let records: Vec<Vec<u8>> = ips
+        .iter()
+        .enumerate()
+        .map(|(i, ip)| record(*ip, &format!("C{}", i), &format!("A{}", i)))
+        .collect();
+
+    let index_start = 8u32;
+    let index_end = index_start + (ips.len() as u32 - 1) * INDEX_LEN as u32;
+
+    let mut content_offset = index_start + ips.len() as u32 * INDEX_LEN as u32;
+    let mut content_offsets = Vec::new();
+    for r in &records {
+        content_offsets.push(content_offset);
+        content_offset += r.len() as u32;
+    }
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&index_start.to_le_bytes());
+    buf.extend_from_slice(&index_end.to_le_bytes());
+
+    for (ip, content_offset) in ips.iter().zip(&content_offsets) {
+        buf.extend_from_slice(&ip.to_le_bytes());
+        buf.extend_from_slice(&content_offset.to_le_bytes()[0..3]);
+    }
+
+    for r in &records {
+        buf.extend_from_slice(r);
+    }
+
+    buf
+}
+
+#[test]
+fn test_search_index_floor_match() {
+    let data = build_synthetic_dat(&[10, 20, 30]);
+    let path = std::env::temp_dir().join("qqwry_test_floor_match.dat");
+    std::fs::write(&path, &data).unwrap();
+    let db = IPDB::open_in_memory(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+
+    let exact = db.find(Ipv4Addr::new(10, 0, 0, 0)).unwrap();
+    assert_eq!(exact.country, "C0");
+    assert_eq!(exact.start, Ipv4Addr::new(10, 0, 0, 0));
+    assert_eq!(exact.end, Ipv4Addr::new(19, 255, 255, 255));
+
+    // 15.0.0.0 sits strictly between the 10.0.0.0 and 20.0.0.0 index
+    // entries, so it must floor-match the lower one instead of NotFound.
+    let interior = db.find(Ipv4Addr::new(15, 0, 0, 0)).unwrap();
+    assert_eq!(interior.country, "C0");
+
+    let last = db.find(Ipv4Addr::new(30, 0, 0, 0)).unwrap();
+    assert_eq!(last.country, "C2");
+    assert_eq!(last.end, Ipv4Addr::new(255, 255, 255, 255));
+
+    assert!(db.find(Ipv4Addr::new(5, 0, 0, 0)).is_err());
+}
+
+/// `search_index`'s `ip_num` domain treats `Ipv4Addr`'s octets as a
+/// little-endian-encoded integer (see `convert_ipv4_to_u32`), so the
+/// `index_table` bucket an address falls into is its *last* dotted octet,
+/// not its first. Build an `Ipv4Addr` whose `ip_num` equals `n` by laying
+/// `n`'s little-endian bytes straight into the octets.
+#[cfg(test)]
+fn ip_with_num(n: u32) -> Ipv4Addr {
+    let b = n.to_le_bytes();
+    Ipv4Addr::new(b[0], b[1], b[2], b[3])
+}
+
+#[test]
+fn test_search_index_floor_match_across_buckets() {
+    // Two entries whose *own* start IPs land in different index_table
+    // buckets (bucket = ip_num >> 24): ip_num 1 (bucket 0) and ip_num
+    // 1 + 50 * 2^24 (bucket 50). A query landing in a gap bucket that
+    // neither entry owns (e.g. bucket 30) must still floor-match into the
+    // nearest lower bucket instead of reporting NotFound, since an
+    // entry's covered range isn't confined to its own bucket.
+    let entry_a = 1u32;
+    let entry_b = 1u32 + (50u32 << 24);
+    let data = build_synthetic_dat(&[entry_a, entry_b]);
+    let path = std::env::temp_dir().join("qqwry_test_floor_match_across_buckets.dat");
+    std::fs::write(&path, &data).unwrap();
+    let db = IPDB::open_in_memory(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+
+    let gap_below_b = ip_with_num(entry_a + (30u32 << 24));
+    let record = db.find(gap_below_b).unwrap();
+    assert_eq!(record.country, "C0");
+
+    let gap_above_b = ip_with_num(entry_b + (50u32 << 24));
+    let record = db.find(gap_above_b).unwrap();
+    assert_eq!(record.country, "C1");
+    assert_eq!(record.end, Ipv4Addr::new(255, 255, 255, 255));
+}
+
+#[test]
+fn test_record_range_interior_ip() {
+    // An exact-match search only ever succeeds on an IP that is itself an
+    // index entry's start, which would leave `covers`/`to_cidrs`
+    // unreachable for any other address; `search_index` floor-matches. Exercise them here against
+    // an interior IP to make sure the range they report is actually usable.
+    let data = build_synthetic_dat(&[10, 20, 30]);
+    let path = std::env::temp_dir().join("qqwry_test_record_range_interior.dat");
+    std::fs::write(&path, &data).unwrap();
+    let db = IPDB::open_in_memory(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+
+    let interior_ip = Ipv4Addr::new(15, 0, 0, 0);
+    let record = db.find(interior_ip).unwrap();
+
+    assert!(record.covers(interior_ip));
+    assert_eq!(record.start, Ipv4Addr::new(10, 0, 0, 0));
+    assert_eq!(record.end, Ipv4Addr::new(19, 255, 255, 255));
+
+    let cidrs = record.to_cidrs();
+    assert!(!cidrs.is_empty());
+
+    let in_some_block = |addr: Ipv4Addr| {
+        cidrs.iter().any(|(net, prefix)| {
+            let block_size = 1u64 << (32 - *prefix as u32);
+            let net_val = u32::from(*net) as u64;
+            let addr_val = u32::from(addr) as u64;
+            (net_val..net_val + block_size).contains(&addr_val)
+        })
+    };
+    for octet in [10u8, 15, 19] {
+        assert!(in_some_block(Ipv4Addr::new(octet, 0, 0, 0)));
+    }
 }